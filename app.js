@@ -1,14 +1,22 @@
 
 const { decks } = require('cards');
 const { Party } = require('./party.js');
+const { Round } = require('./round.js');
+const { CHAT_ERRORS } = require('./chat.js');
+const { Metrics } = require('./metrics.js');
 //const { player } = require('./player.js');
-const { print_players_hands, str_cards, json_hand, check_play, get_card_from_id, get_card_id, get_cards_from_ids } = require('./utils.js');
+const { print_players_hands, str_cards, json_hand, check_play_reason, PLAY_ERRORS, get_card_from_id, get_card_id, get_cards_from_ids, get_card_points, analyze_hand, suggest_plays, estimate_win_probability } = require('./utils.js');
 
 // Create a standard 52 card deck + 2 jokers
 const deck = new decks.StandardDeck({ jokers: 2 });
 
 // Create party
-const party = new Party(deck);
+var party = new Party(deck, {
+    nb_cards_in_hand: 2,
+    zapzap_max_points: 5,
+    turn_timer: 0,
+    allow_spectators: true
+});
 
 // Add players
 party.add_player("Vincent");
@@ -17,8 +25,8 @@ party.add_player("Simon  ");
 party.add_player("Lyo    ");
 party.add_player("Laurent");
 
-// Start new round with 5 cards
-var round = party.start_round(2, 0);
+// Start new round
+var round = party.start_round();
 
 // print party status
 print_players_hands(party.players);
@@ -27,13 +35,58 @@ console.log("Turn : "+ party.current_round.turn);
 const express = require('express');
 const events = require('events');
 var emitter = new events.EventEmitter();
+
+// Last events kept to replay them to clients reconnecting with Last-Event-ID
+const EVENTS_BUFFER_SIZE = 100;
+var last_event_id = 0;
+var events_buffer = [];
+
+function emit_event(data) {
+    var event = {id: ++last_event_id, data: data};
+    events_buffer.push(event);
+    if (events_buffer.length > EVENTS_BUFFER_SIZE)
+        events_buffer.shift();
+    emitter.emit('event', event);
+}
+
+// METRICS
+var sse_connections = 0;
+const metrics = new Metrics();
+metrics.counter('zapzap_http_requests_total', 'HTTP requests by route and status');
+metrics.histogram('zapzap_http_request_duration_seconds', 'HTTP request latency by route');
+metrics.gauge('zapzap_sse_connections', 'Open event stream connections', () => sse_connections);
+metrics.gauge('zapzap_parties_active', 'Parties being played', () => party.current_round.action!=Round.ACTION_ZAPZAP ? 1 : 0);
+metrics.gauge('zapzap_spectators', 'Spectators watching the party', () => party.nb_spectators);
+metrics.counter('zapzap_game_actions_total', 'Game actions by type');
+metrics.counter('zapzap_chat_messages_total', 'Chat messages by channel');
+metrics.counter('zapzap_reactions_total', 'Quick reactions sent');
+
 var app = express();
+// event stream connections last as long as the page, zapzap_sse_connections counts them
+app.use(function(req, res, next) {
+    if (req.path=='/suscribeupdate')
+        return next();
+    var start = process.hrtime.bigint();
+    res.on('finish', function() {
+        var route = req.route ? req.route.path : "other";
+        metrics.inc('zapzap_http_requests_total', {method: req.method, route: route, status: res.statusCode});
+        metrics.observe('zapzap_http_request_duration_seconds', Number(process.hrtime.bigint() - start) / 1e9, {route: route});
+    });
+    next();
+});
 app.use('/node_modules/deck-of-cards', express.static('node_modules/deck-of-cards'));
 app.use('/node_modules/jquery/dist', express.static('node_modules/jquery/dist'));
 app.use('/public', express.static('public'));
 
 
 app.get('/suscribeupdate', function(req, res){
+    var spectator = req.query.spectator != undefined;
+    if (spectator && !party.allow_spectators) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(403).send('Spectateurs interdits !');
+        return;
+    }
+
 	res.writeHead(200, {
 		'Content-Type': 'text/event-stream',
 		'Cache-Control': 'no-cache',
@@ -45,24 +98,68 @@ app.get('/suscribeupdate', function(req, res){
 		res.write('\n');
 	};
     const hbt = setInterval(nln, 15000);
+    sse_connections++;
+
+    if (spectator)
+        party.add_spectator();
     
-    var onEvent = function(data) {
+    var onEvent = function(event) {
         res.write('retry: 500\n');
+        res.write(`id: ${event.id}\n`);
 		res.write('event: event\n');
+		res.write(`data: ${JSON.stringify(event.data)}\n\n`);
+    }
+
+    // Replay events missed since the client was disconnected
+    var client_last_id = parseInt(req.get('Last-Event-ID'), 10);
+    if (!isNaN(client_last_id)) {
+        events_buffer.filter(event => event.id > client_last_id).forEach(onEvent);
+    }
+
+    var onTimer = function(data) {
+		res.write('event: timer\n');
+		res.write(`data: ${JSON.stringify(data)}\n\n`);
+    }
+
+    // spectators chat is only sent to spectators
+    var onChat = function(data) {
+        if (data.channel=="spectators" && !spectator)
+            return;
+		res.write('event: chat\n');
 		res.write(`data: ${JSON.stringify(data)}\n\n`);
     }
 
     emitter.on('event', onEvent);
+    emitter.on('timer', onTimer);
+    var onReaction = function(data) {
+		res.write('event: reaction\n');
+		res.write(`data: ${JSON.stringify(data)}\n\n`);
+    }
+
+    emitter.on('chat', onChat);
+    emitter.on('reaction', onReaction);
     
     // Clear heartbeat and listener
     req.on('close', function() {
 		clearInterval(hbt);
+		sse_connections--;
 		emitter.removeListener('event', onEvent);
+		emitter.removeListener('timer', onTimer);
+		emitter.removeListener('chat', onChat);
+		emitter.removeListener('reaction', onReaction);
+		if (spectator)
+			party.remove_spectator();
 	});
 });
 
 // VIEW
 app.get('/', function(req, res) {
+    // without player id, watch the game as a spectator
+    if (req.query.id == undefined && !party.allow_spectators) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(403).send('Spectateurs interdits !');
+        return;
+    }
     res.render('hand.ejs', {"player": req.query.id});
 });
 
@@ -74,38 +171,120 @@ app.get('/player/:id/hand', function(req, res) {
     res.send(JSON.stringify(json_hand(party.players[req.params.id].hand, party.deck)));
 });
 
+// HAND ANALYSIS
+app.get('/player/:id/analysis', function(req, res) {
+    var player = party.players[req.params.id];
+    if (player==undefined) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Joueur introuvable !');
+        return;
+    }
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify(analyze_hand(player.hand, party.deck, party.settings.zapzap_max_points)));
+});
+
+// HINT
+app.get('/player/:id/hint', function(req, res) {
+    var player = party.players[req.params.id];
+    var top_k = req.query.top_k!=undefined ? Number(req.query.top_k) : undefined;
+    if (player==undefined) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Joueur introuvable !');
+        return;
+    }
+    res.setHeader('Content-Type', 'text/json');
+    if (top_k!=undefined && !(Number.isInteger(top_k) && top_k>0)) {
+        res.status(400).send(JSON.stringify({error: "invalid_top_k", message: "top_k must be a positive integer"}));
+        return;
+    }
+    res.send(JSON.stringify(suggest_plays(player.hand, party.deck, top_k)));
+});
+
+// GAME ACTIONS
+function play_cards(player, cards) {
+    // remove cards from player hand
+    player.play(cards);
+    // play card on discard pile
+    party.current_round.play_cards(cards);
+    party.current_round.log_action({type: "play", player: player.id, cards: json_hand(cards, party.deck)});
+    metrics.inc('zapzap_game_actions_total', {action: "play"});
+
+    emit_event({id: player.id});
+
+    console.log("Turn "+ party.current_round.turn + " : "+player.name + " play " + str_cards(cards));
+}
+
+function draw_card(player, card) {
+    var from = card==undefined ? "deck" : "played";
+    card = party.current_round.draw(card);
+    player.draw(card);
+    party.current_round.log_action({type: "draw", player: player.id, card: get_card_id(card, party.deck), from: from});
+    metrics.inc('zapzap_game_actions_total', {action: "draw"});
+
+    console.log("Turn "+ party.current_round.turn + " : "+ player.name + " draw " + str_cards([card]));
+    print_players_hands(party.players);
+
+    party.current_round.next_turn();
+
+    emit_event({id: player.id});
+    return card;
+}
+
+// WIN PROBABILITY
+// Known cards are the player hand, the cards visible on the table and the
+// discarded ones
+app.get('/player/:id/win_probability', function(req, res) {
+    var player = party.players[req.params.id];
+    var samples = req.query.samples!=undefined ? Number(req.query.samples) : 1000;
+    if (player==undefined) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Joueur introuvable !');
+        return;
+    }
+    res.setHeader('Content-Type', 'text/json');
+    if (!(Number.isInteger(samples) && samples>0 && samples<=10000)) {
+        res.status(400).send(JSON.stringify({error: "invalid_samples", message: "samples must be an integer between 1 and 10000"}));
+        return;
+    }
+
+    var round = party.current_round;
+    var opponents_hand_sizes = party.players.filter(opponent => opponent!=player).map(opponent => opponent.hand.length);
+    var known_cards = round.last_cards_played.concat(round.cards_played, round.discarded_cards);
+    res.send(JSON.stringify({
+        probability: estimate_win_probability(player.hand, opponents_hand_sizes, known_cards, party.deck, samples),
+        samples: samples
+    }));
+});
+
 // PLAY
 app.get('/player/:id/play', function(req, res) {
-    var ret = true;
+    var reason = undefined;
+    var player = party.players[req.params.id];
 
     if (req.query.cards) {
 
         // parse request
         var cards = get_cards_from_ids(req.query.cards, party.deck);
-        var player = party.players[req.params.id];
 
         // check played cards
-        if (check_play(cards, player)) {
-            // remove cards from player hand
-            player.play(cards);
-            // play card on discard pile
-            party.current_round.play_cards(cards);
-
-            emitter.emit('event', {id: req.params.id});
+        reason = check_play_reason(cards, player);
+        if (reason == undefined) {
+            play_cards(player, cards);
         } else {
-            console.log("Turn "+ party.current_round.turn + " : "+player.name + " incorrect play");
-            ret = false;
+            console.log("Turn "+ party.current_round.turn + " : "+player.name + " incorrect play : " + reason);
         }
-
-        console.log("Turn "+ party.current_round.turn + " : "+player.name + " play " + str_cards(cards));
         //print_players_hands(party.players);
     }
     else {
         console.log("Turn "+ party.current_round.turn + " : "+player.name + " play undefined");
-        ret = false;
+        reason = "no_cards";
     }
 
     res.setHeader('Content-Type', 'text/json');
+    if (reason != undefined) {
+        res.status(400).send(JSON.stringify({error: reason, message: PLAY_ERRORS[reason]}));
+        return;
+    }
     res.send(JSON.stringify(json_hand(party.players[req.params.id].hand, party.deck)));
 });
 
@@ -120,15 +299,7 @@ app.get('/player/:id/draw', function(req, res) {
         card = get_card_from_id(req.query.card, party.deck);
     var player = party.players[req.params.id];
 
-    card = party.current_round.draw(card);
-    player.draw(card);
-    
-    console.log("Turn "+ party.current_round.turn + " : "+ player.name + " draw " + str_cards([card]));
-    print_players_hands(party.players);
-
-    party.current_round.next_turn();
-
-    emitter.emit('event', {id: req.params.id});
+    card = draw_card(player, card);
 
     res.setHeader('Content-Type', 'text/json');
     res.send(JSON.stringify({draw: get_card_id(card, party.deck), hand: json_hand(party.players[req.params.id].hand, party.deck)}));
@@ -141,15 +312,22 @@ app.get('/player/:id/zapzap', function(req, res) {
     // parse request
     var player = party.players[req.params.id];
 
-    if (player.hand_points>5) {
+    if (player.hand_points>party.settings.zapzap_max_points) {
         ret = false;
     } else {      
         party.current_round.zapzap(party.players, req.params.id);
+        party.current_round.log_action({
+            type: "zapzap",
+            player: player.id,
+            hands: party.players.map(player => json_hand(player.hand, party.deck)),
+            score: party.current_round.score
+        });
         
+        metrics.inc('zapzap_game_actions_total', {action: "zapzap"});
         console.log("Turn "+ party.current_round.turn + " : "+ player.name + " zapzap ");
         print_players_hands(party.players);
 
-        emitter.emit('event', {id: req.params.id});
+        emit_event({id: req.params.id});
     }
     res.setHeader('Content-Type', 'text/json');
     res.send(JSON.stringify({ret}));
@@ -157,11 +335,256 @@ app.get('/player/:id/zapzap', function(req, res) {
 
 
 
+// TURN TIMER
+// Each second, send the remaining time of the current turn and play for the
+// current player when it is over : lowest single card then draw from deck, or
+// the lowest visible card once the deck is empty
+function lowest_card(cards) {
+    return cards.reduce((lowest, card) =>
+        get_card_points(card, true) < get_card_points(lowest, true) ? card : lowest);
+}
+
+var timer_round = undefined;
+var timer_turn = undefined;
+var turn_deadline = undefined;
+setInterval(function() {
+    var round = party.current_round;
+    if (party.turn_timer<=0 || round.action==Round.ACTION_ZAPZAP)
+        return;
+
+    if (round!=timer_round || round.turn!=timer_turn) {
+        timer_round = round;
+        timer_turn = round.turn;
+        turn_deadline = Date.now() + party.turn_timer*1000;
+    }
+    // timer stopped for this turn
+    if (turn_deadline==undefined)
+        return;
+
+    var player = party.players[round.turn%party.nb_players];
+    var remaining = Math.ceil((turn_deadline - Date.now())/1000);
+    if (remaining>0) {
+        emitter.emit('timer', {id: player.id, remaining: remaining});
+        return;
+    }
+
+    console.log("Turn "+ round.turn + " : "+ player.name + " timeout");
+    metrics.inc('zapzap_game_actions_total', {action: "timeout"});
+    try {
+        var card = undefined;
+        if (party.deck.remainingLength==0) {
+            if (round.last_cards_played.length==0) {
+                console.log("Turn "+ round.turn + " : no card to draw, timer stopped");
+                turn_deadline = undefined;
+                return;
+            }
+            card = lowest_card(round.last_cards_played);
+        }
+        if (round.action==Round.ACTION_DRAW && player.hand.length>0)
+            play_cards(player, [lowest_card(player.hand)]);
+        draw_card(player, card);
+    } catch (error) {
+        console.log("ERROR : turn timer : " + error);
+        turn_deadline = undefined;
+    }
+}, 1000);
+
+// CHAT
+function post_chat(res, chat, channel, sender, author, text) {
+    res.setHeader('Content-Type', 'text/json');
+    var reason = chat.check_message(sender, author, text);
+    if (reason != undefined) {
+        res.status(400).send(JSON.stringify({error: reason, message: CHAT_ERRORS[reason]}));
+        return;
+    }
+    var message = chat.post(sender, author, text);
+    emitter.emit('chat', Object.assign({channel: channel}, message));
+    metrics.inc('zapzap_chat_messages_total', {channel: channel});
+    res.send(JSON.stringify(message));
+}
+
+app.get('/player/:id/chat', function(req, res) {
+    var player = party.players[req.params.id];
+    if (player==undefined) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Joueur introuvable !');
+        return;
+    }
+    post_chat(res, party.chat, "players", "player:" + player.id, player.name, req.query.message);
+});
+
+app.get('/spectator/chat', function(req, res) {
+    if (!party.allow_spectators) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(403).send('Spectateurs interdits !');
+        return;
+    }
+    // spectators choose their name, rate limit them by address
+    post_chat(res, party.spectator_chat, "spectators", "spectator:" + req.ip, req.query.name, req.query.message);
+});
+
+// spectators chat history is only for spectators, as its events
+app.get('/party/chat', function(req, res) {
+    var spectator = req.query.spectator != undefined;
+    if (spectator && !party.allow_spectators) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(403).send('Spectateurs interdits !');
+        return;
+    }
+    var chat = spectator ? party.spectator_chat : party.chat;
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify(chat.messages));
+});
+
+// REACTIONS
+const REACTION_ERRORS = {
+    unknown_reaction: "Unknown reaction",
+    too_many_reactions: "Too many reactions this turn"
+};
+
+app.get('/reactions', function(req, res) {
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify(Party.REACTIONS));
+});
+
+app.get('/player/:id/react', function(req, res) {
+    var player = party.players[req.params.id];
+    var reaction = req.query.reaction;
+    if (player==undefined) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Joueur introuvable !');
+        return;
+    }
+
+    res.setHeader('Content-Type', 'text/json');
+    var reason = party.check_reaction(player.id, reaction);
+    if (reason != undefined) {
+        res.status(400).send(JSON.stringify({error: reason, message: REACTION_ERRORS[reason]}));
+        return;
+    }
+    party.add_reaction(player.id);
+
+    var data = {id: player.id, name: player.name, reaction: reaction, text: Party.REACTIONS[reaction]};
+    emitter.emit('reaction', data);
+    metrics.inc('zapzap_reactions_total');
+    res.send(JSON.stringify(data));
+});
+
+// REMATCH
+// Finished parties are kept for their replay
+const FINISHED_PARTIES_SIZE = 50;
+var finished_parties = [];
+
+app.get('/player/:id/rematch', function(req, res) {
+    var ret = true;
+    var player = party.players[req.params.id];
+    if (player==undefined) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Joueur introuvable !');
+        return;
+    }
+
+    if (party.current_round.action!=Round.ACTION_ZAPZAP) {
+        ret = false;
+    } else if (party.accept_rematch(player.id)) {
+        finished_parties.push(party);
+        if (finished_parties.length > FINISHED_PARTIES_SIZE)
+            finished_parties.shift();
+        party = party.rematch();
+        console.log("Rematch : new party");
+        print_players_hands(party.players);
+        emit_event({id: player.id, rematch: true});
+    } else {
+        console.log(player.name + " accept rematch");
+        emit_event({id: player.id});
+    }
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify({ret}));
+});
+
+// REPLAY
+function send_replay(res, replay_party) {
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify(replay_party.rounds.map(round => round.public_actions)));
+}
+
+function send_replay_step(res, replay_party, round_param, step_param) {
+    var round_index = parseInt(round_param, 10);
+    var step = parseInt(step_param, 10);
+    var round = Number.isInteger(round_index) && round_index>=0 && round_index<replay_party.rounds.length ?
+        replay_party.rounds[round_index] : undefined;
+    if (round==undefined || !Number.isInteger(step) || step<0 || step>=round.actions.length) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Action introuvable !');
+        return;
+    }
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify({
+        action: round.public_actions[step],
+        step: step,
+        nb_steps: round.actions.length,
+        next: step+1<round.actions.length ? step+1 : null
+    }));
+}
+
+// Finished party from its id, 404 if unknown or no longer in the history
+function get_finished_party(req, res) {
+    var id = Number(req.params.party);
+    var finished_party = finished_parties.find(finished_party => finished_party.id===id);
+    if (finished_party==undefined) {
+        res.setHeader('Content-Type', 'text/plain');
+        res.status(404).send('Partie introuvable !');
+        return undefined;
+    }
+    return finished_party;
+}
+
+app.get('/party/replay', function(req, res) {
+    send_replay(res, party);
+});
+
+app.get('/party/replay/:round/:step', function(req, res) {
+    send_replay_step(res, party, req.params.round, req.params.step);
+});
+
+app.get('/history', function(req, res) {
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify(finished_parties.map(finished_party => {
+        return {
+            party: finished_party.id,
+            players: finished_party.players.map(player => player.name),
+            nb_rounds: finished_party.rounds.length
+        };
+    })));
+});
+
+app.get('/history/:party/replay', function(req, res) {
+    var finished_party = get_finished_party(req, res);
+    if (finished_party!=undefined)
+        send_replay(res, finished_party);
+});
+
+app.get('/history/:party/replay/:round/:step', function(req, res) {
+    var finished_party = get_finished_party(req, res);
+    if (finished_party!=undefined)
+        send_replay_step(res, finished_party, req.params.round, req.params.step);
+});
+
+app.get('/party/settings', function(req, res) {
+    res.setHeader('Content-Type', 'text/json');
+    res.send(JSON.stringify(party.settings));
+});
+
 app.get('/party', function(req, res) {
     res.setHeader('Content-Type', 'text/json');
     res.send(party.json_string);
 });
 
+app.get('/metrics', function(req, res) {
+    res.setHeader('Content-Type', 'text/plain; version=0.0.4');
+    res.send(metrics.render());
+});
+
 app.use(function(req, res, next){
     res.setHeader('Content-Type', 'text/plain');
     res.status(404).send('Page introuvable !');
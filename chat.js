@@ -0,0 +1,59 @@
+// Reasons returned by check_message for a refused message
+const CHAT_ERRORS = {
+    invalid_name: "Name must be 1 to 20 characters",
+    empty_message: "Message is empty",
+    message_too_long: "Message is too long",
+    rate_limited: "Too many messages, wait a little"
+};
+
+class Chat {
+    constructor(history_size = 50, min_interval = 1000) {
+        this._messages = [];
+        this._history_size = history_size;
+        this._min_interval = min_interval;
+        // last post time by sender
+        this._last_post = new Map();
+        this._filters = [];
+    }
+
+    get messages() {
+        return this._messages;
+    }
+
+    // filter(text) returns the text to post, e.g. with profanity masked
+    add_filter(filter) {
+        this._filters.push(filter);
+    }
+
+    // sender identifies who posts for the rate limit, author is the displayed name
+    check_message(sender, author, text) {
+        if (typeof author!="string" || author.trim().length==0 || author.trim().length>Chat.MAX_NAME_LENGTH)
+            return "invalid_name";
+        if (typeof text!="string" || text.trim().length==0)
+            return "empty_message";
+        if (text.length>Chat.MAX_LENGTH)
+            return "message_too_long";
+        if (Date.now() - (this._last_post.get(sender) || 0) < this._min_interval)
+            return "rate_limited";
+        return undefined;
+    }
+
+    post(sender, author, text) {
+        this._last_post.set(sender, Date.now());
+        var message = {
+            author: author.trim(),
+            text: this._filters.reduce((text, filter) => filter(text), text.trim()),
+            time: Date.now()
+        };
+        this._messages.push(message);
+        if (this._messages.length>this._history_size)
+            this._messages.shift();
+        return message;
+    }
+}
+
+Chat.MAX_LENGTH = 200;
+Chat.MAX_NAME_LENGTH = 20;
+
+exports.Chat = Chat;
+exports.CHAT_ERRORS = CHAT_ERRORS;
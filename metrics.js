@@ -0,0 +1,84 @@
+// Counters, gauges and histograms rendered in the Prometheus text format
+
+const DEFAULT_BUCKETS = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1, 2.5, 5];
+
+// label values escape backslash, double quote and line feed as \\, \" and \n
+function escape_label(value) {
+    return String(value).replace(/\\/g, '\\\\').replace(/"/g, '\\"').replace(/\n/g, '\\n');
+}
+
+function label_key(labels) {
+    return Object.keys(labels).sort().map(name => `${name}="${escape_label(labels[name])}"`).join(',');
+}
+
+class Metrics {
+    constructor() {
+        this._metrics = {};
+    }
+
+    _metric(name, type, help) {
+        if (this._metrics[name]==undefined)
+            this._metrics[name] = {type: type, help: help, values: {}};
+        return this._metrics[name];
+    }
+
+    counter(name, help) {
+        this._metric(name, "counter", help);
+    }
+
+    gauge(name, help, collect = undefined) {
+        this._metric(name, "gauge", help).collect = collect;
+    }
+
+    histogram(name, help, buckets = DEFAULT_BUCKETS) {
+        this._metric(name, "histogram", help).buckets = buckets;
+    }
+
+    inc(name, labels = {}, value = 1) {
+        var values = this._metrics[name].values;
+        var key = label_key(labels);
+        values[key] = (values[key] || 0) + value;
+    }
+
+    observe(name, value, labels = {}) {
+        var metric = this._metrics[name];
+        var key = label_key(labels);
+        if (metric.values[key]==undefined)
+            metric.values[key] = {labels: labels, buckets: metric.buckets.map(() => 0), sum: 0, count: 0};
+        var histogram = metric.values[key];
+        metric.buckets.forEach((bucket, i) => {
+            if (value<=bucket)
+                histogram.buckets[i]++;
+        });
+        histogram.sum += value;
+        histogram.count++;
+    }
+
+    render() {
+        var lines = [];
+        for (let name in this._metrics) {
+            var metric = this._metrics[name];
+            if (metric.collect!=undefined)
+                metric.values = {"": metric.collect()};
+            lines.push(`# HELP ${name} ${metric.help}`);
+            lines.push(`# TYPE ${name} ${metric.type}`);
+            for (let key in metric.values) {
+                var value = metric.values[key];
+                if (metric.type!="histogram") {
+                    lines.push(key=="" ? `${name} ${value}` : `${name}{${key}} ${value}`);
+                    continue;
+                }
+                var prefix = key=="" ? "" : key + ",";
+                metric.buckets.forEach((bucket, i) => {
+                    lines.push(`${name}_bucket{${prefix}le="${bucket}"} ${value.buckets[i]}`);
+                });
+                lines.push(`${name}_bucket{${prefix}le="+Inf"} ${value.count}`);
+                lines.push(key=="" ? `${name}_sum ${value.sum}` : `${name}_sum{${key}} ${value.sum}`);
+                lines.push(key=="" ? `${name}_count ${value.count}` : `${name}_count{${key}} ${value.count}`);
+            }
+        }
+        return lines.join("\n") + "\n";
+    }
+}
+
+exports.Metrics = Metrics;
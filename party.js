@@ -1,13 +1,56 @@
 
 const { Player } = require('./player.js');
 const { Round } = require('./round.js');
+const { Chat } = require('./chat.js');
 const { json_hand } = require('./utils.js');
 
+// Returns the reason why party settings are incorrect, undefined if they are valid
+function check_settings(settings) {
+    var is_int = (value, min, max) => Number.isInteger(value) && value>=min && value<=max;
+
+    if (!is_int(settings.nb_cards_in_hand, 1, 10))
+        return "nb_cards_in_hand must be between 1 and 10";
+    if (!is_int(settings.zapzap_max_points, 0, 130))
+        return "zapzap_max_points must be between 0 and 130";
+    if (!is_int(settings.max_players, 2, 8))
+        return "max_players must be between 2 and 8";
+    // the deck must hold every hand and the first card played
+    if (settings.max_players*settings.nb_cards_in_hand >= 54)
+        return "not enough cards for max_players hands of nb_cards_in_hand";
+    if (!is_int(settings.turn_timer, 0, 3600))
+        return "turn_timer must be between 0 and 3600 seconds";
+    if (typeof settings.allow_spectators != "boolean")
+        return "allow_spectators must be a boolean";
+    return undefined;
+}
+
 class Party {
-    constructor(deck) {
+    // previous_party is the finished party of a rematch, whose spectators and
+    // chats carry over
+    constructor(deck, settings = {}, previous_party = undefined) {
+        this._settings = Object.assign({}, Party.DEFAULT_SETTINGS, settings);
+        var error = check_settings(this._settings);
+        if (error != undefined)
+            throw new Error("Invalid party settings : " + error);
+
+        this._id = ++Party.last_id;
         this._players = [];
         this._rounds = [];
         this._deck = deck;
+        this._nb_spectators = previous_party ? previous_party.nb_spectators : 0;
+        this._rematch_accepted = [];
+        this._chat = previous_party ? previous_party.chat : new Chat();
+        this._spectator_chat = previous_party ? previous_party.spectator_chat : new Chat();
+        this._reactions = {};
+    }
+
+    // unique among the parties of the server, increasing with their creation
+    get id() {
+        return this._id;
+    }
+
+    get settings() {
+        return this._settings;
     }
 
     get rounds() {
@@ -30,7 +73,53 @@ class Party {
         return this._players.length;
     }
 
-    start_round(nb_cards_in_hand, first_player) {
+    get allow_spectators() {
+        return this._settings.allow_spectators;
+    }
+
+    // seconds to play a turn, 0 to disable
+    get turn_timer() {
+        return this._settings.turn_timer;
+    }
+
+    get chat() {
+        return this._chat;
+    }
+
+    get spectator_chat() {
+        return this._spectator_chat;
+    }
+
+    // Returns the reason why the player can't send this reaction, undefined if allowed
+    check_reaction(player_id, reaction) {
+        if (!Object.prototype.hasOwnProperty.call(Party.REACTIONS, reaction))
+            return "unknown_reaction";
+        var sent = this._reactions[player_id];
+        if (sent!=undefined && sent.turn==this.current_round.turn && sent.count>=Party.REACTIONS_PER_TURN)
+            return "too_many_reactions";
+        return undefined;
+    }
+
+    add_reaction(player_id) {
+        var sent = this._reactions[player_id];
+        if (sent==undefined || sent.turn!=this.current_round.turn)
+            sent = this._reactions[player_id] = {turn: this.current_round.turn, count: 0};
+        sent.count++;
+    }
+
+    get nb_spectators() {
+        return this._nb_spectators;
+    }
+
+    add_spectator() {
+        this._nb_spectators++;
+    }
+
+    remove_spectator() {
+        this._nb_spectators--;
+    }
+
+    start_round(nb_cards_in_hand = this._settings.nb_cards_in_hand, first_player = 0) {
         // start the new round
         var round = new Round(nb_cards_in_hand, first_player, this._deck);
         this._rounds.push(round);
@@ -42,10 +131,35 @@ class Party {
             player.sethand(cards);
         });
 
+        round.log_action({
+            type: "deal",
+            hands: this._players.map(player => json_hand(player.hand, this._deck)),
+            last_cards_played: json_hand(round.last_cards_played, this._deck)
+        });
+
         return round;
     }
 
+    // Returns true once every player accepted the rematch
+    accept_rematch(player_id) {
+        if (!this._rematch_accepted.includes(player_id))
+            this._rematch_accepted.push(player_id);
+        return this._rematch_accepted.length==this._players.length;
+    }
+
+    // New party with the same players and settings, and fresh scores
+    rematch() {
+        var party = new Party(this._deck, this._settings, this);
+        this._players.forEach(player => party.add_player(player.name));
+        party.start_round(this.current_round.nb_cards_in_hand, 0);
+        return party;
+    }
+
     add_player(name) {
+        if (this._players.length >= this._settings.max_players) {
+            console.log("ERROR : party is full, " + name + " can't join");
+            return undefined;
+        }
         var player = new Player(name, this._players.length);
         this._players.push(player);
         return player;
@@ -62,12 +176,16 @@ class Party {
             players_array.push(json_player);
         });
         var json = {
+            "id": this._id,
             "nb_players": this.nb_players,
             "current_turn": this.current_round.turn,
             "card_in_deck": this._deck.remainingLength,
             "last_cards_played": json_hand(this.current_round.last_cards_played),
             "cards_played": json_hand(this.current_round.cards_played),
             "players": players_array,
+            "nb_spectators": this._nb_spectators,
+            "settings": this._settings,
+            "rematch_accepted": this._rematch_accepted,
             "action": this.current_round.action
         }
 
@@ -76,4 +194,27 @@ class Party {
     }
 }
 
-exports.Party = Party;
\ No newline at end of file
+// id of the last party created
+Party.last_id = 0;
+
+Party.DEFAULT_SETTINGS = {
+    nb_cards_in_hand: 5,
+    zapzap_max_points: 5,
+    max_players: 8,
+    turn_timer: 0,
+    allow_spectators: true
+};
+
+// Quick reactions players can send during a game
+Party.REACTIONS = {
+    thumbs_up: "👍",
+    laugh: "😂",
+    nice_play: "Nice play!",
+    nice_zapzap: "Nice zapzap!",
+    hurry_up: "Hurry up!",
+    oops: "Oops!"
+};
+Party.REACTIONS_PER_TURN = 3;
+
+exports.Party = Party;
+exports.check_settings = check_settings;
\ No newline at end of file
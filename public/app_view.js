@@ -133,6 +133,8 @@ function update_game() {
     console.log("update_game");
     if (game_deck!=undefined)
         game_deck.unmount();
+    // remove scores shown at the end of the previous round
+    $(game_container).find('.player_name').remove();
     game_deck = Deck(true);
     
     $.getJSON('/party', function( data ) {    
@@ -149,6 +151,10 @@ function update_game() {
         // disable all buttons
         $(':button').prop('disabled', true);
 
+        // Rematch can be accepted by every player once the round is over
+        if (data.action=="zapzap" && player_id!=undefined && !data.rematch_accepted.includes(player_id))
+            $rematch.disabled = '';
+
         // Enable Buttons for current player
         if (data.current_turn%data.nb_players==player_id) {
             switch(data.action) {
@@ -212,14 +218,49 @@ var $bysuit = null;
 var $fan = null;
 var $zapzap = null;
 var $update = null;
+var evtSource = null;
+var $rematch = null;
+var $timer = null;
+
+function build_topbar($topbar, spectator=false) {
+    $update = document.createElement('button')
+    $timer = document.createElement('span')
+
+    $update.textContent = 'Update'
+
+    $topbar.appendChild($update)
+    // spectators can't play
+    if (!spectator)
+        build_player_buttons($topbar)
+    $topbar.appendChild($timer)
+
+    evtSource = new EventSource('/suscribeupdate' + (spectator ? '?spectator' : ''));
+    evtSource.addEventListener('event', function(evt) {
+        var data = JSON.parse(evt.data);
+        if (data.rematch && player_id!=undefined) {
+            $.getJSON('/player/'+player_id+'/hand', function( hand ) {
+                update_player_hand(hand);
+            });
+        }
+        update_game();
+    },false);
+    evtSource.addEventListener('timer', function(evt) {
+        var data = JSON.parse(evt.data);
+        $timer.textContent = data.remaining + 's';
+    },false);
+
+    $update.addEventListener('click', function () {
+        update_game()
+    });
+}
 
-function build_topbar($topbar) {
+function build_player_buttons($topbar) {
     $draw = document.createElement('button')
     $play = document.createElement('button')
     $bysuit = document.createElement('button')
     $fan = document.createElement('button')
     $zapzap = document.createElement('button')
-    $update = document.createElement('button')
+    $rematch = document.createElement('button')
 
     $play.textContent = 'Play'
     $draw.textContent = 'Draw'
@@ -228,20 +269,14 @@ function build_topbar($topbar) {
     $fan.textContent = 'Fan'
 
     $zapzap.textContent = 'ZapZap'
-    $update.textContent = 'Update'
+    $rematch.textContent = 'Rematch'
 
-    $topbar.appendChild($update)
     $topbar.appendChild($play)
     $topbar.appendChild($bysuit)
     $topbar.appendChild($fan)
     $topbar.appendChild($zapzap)
     $topbar.appendChild($draw)
-
-    const evtSource = new EventSource('/suscribeupdate');
-    evtSource.addEventListener('event', function(evt) {
-        update_game();
-    },false);
-
+    $topbar.appendChild($rematch)
 
     $bysuit.addEventListener('click', function () {
         hand_deck.sort(true) // sort reversed
@@ -271,6 +306,9 @@ function build_topbar($topbar) {
         .done(function( json ) {
             update_player_hand(json);
             //update_game();
+        })
+        .fail(function( jqxhr ) {
+            alert(JSON.parse(jqxhr.responseText).message);
         });
     });
 
@@ -284,6 +322,10 @@ function build_topbar($topbar) {
         });
     });
 
+    $rematch.addEventListener('click', function () {
+        $.getJSON('/player/'+ player_id + '/rematch', {});
+    });
+
     $zapzap.addEventListener('click', function () {
         elements = game_container.getElementsByClassName("draw_select");
         $.getJSON('/player/'+ player_id + '/zapzap', {})
@@ -295,4 +337,70 @@ function build_topbar($topbar) {
     });
 
 
-}
\ No newline at end of file
+}
+
+function add_chat_message($messages, message) {
+    var $message = document.createElement('div');
+    $message.textContent = message.author.trim() + ' : ' + message.text;
+    $messages.appendChild($message);
+    $messages.scrollTop = $messages.scrollHeight;
+}
+
+function build_chat($container, spectator=false) {
+    var $messages = document.createElement('div');
+    var $input = document.createElement('input');
+    $messages.classList.add('chat_messages');
+    $input.placeholder = 'Message';
+    $container.appendChild($messages);
+    $container.appendChild($input);
+
+    $.getJSON('/party/chat', spectator ? { spectator: '' } : {}, function( messages ) {
+        messages.forEach(message => add_chat_message($messages, message));
+    });
+
+    evtSource.addEventListener('chat', function(evt) {
+        add_chat_message($messages, JSON.parse(evt.data));
+    },false);
+
+    evtSource.addEventListener('reaction', function(evt) {
+        var data = JSON.parse(evt.data);
+        add_chat_message($messages, {author: data.name, text: data.text});
+    },false);
+
+    // players can send quick reactions
+    if (!spectator) {
+        var $reactions = document.createElement('div');
+        $reactions.classList.add('chat_reactions');
+        $container.appendChild($reactions);
+        $.getJSON('/reactions', function( reactions ) {
+            for (let reaction in reactions) {
+                var $reaction = document.createElement('span');
+                $reaction.textContent = reactions[reaction];
+                $reaction.addEventListener('click', function () {
+                    $.getJSON('/player/'+ player_id + '/react', { reaction: reaction });
+                });
+                $reactions.appendChild($reaction);
+            }
+        });
+    }
+
+    var spectator_name = undefined;
+    $input.addEventListener('keydown', function (evt) {
+        if (evt.key!='Enter' || $input.value=='')
+            return;
+        var request;
+        if (spectator) {
+            if (spectator_name==undefined)
+                spectator_name = prompt('Name') || 'Spectator';
+            request = $.getJSON('/spectator/chat', { name: spectator_name, message: $input.value });
+        } else {
+            request = $.getJSON('/player/'+ player_id + '/chat', { message: $input.value });
+        }
+        request.done(function() {
+            $input.value = '';
+        })
+        .fail(function( jqxhr ) {
+            alert(JSON.parse(jqxhr.responseText).message);
+        });
+    });
+}
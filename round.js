@@ -10,8 +10,10 @@ class Round {
         this._deck.shuffleAll();
         this._last_cards_played = this._deck.draw();
         this._cards_played = [];
+        this._discarded_cards = [];
         this._player_action = Round.ACTION_DRAW;
         this._score = [];
+        this._actions = [];
     }
 
 
@@ -20,6 +22,10 @@ class Round {
         return this._turn;
     }
 
+    get nb_cards_in_hand() {
+        return this._nb_cards_in_hand;
+    }
+
     get action() {
         return this._player_action;
     }
@@ -40,6 +46,37 @@ class Round {
         return this._cards_played;
     }
 
+    // Cards played this round which went to the discard pile
+    get discarded_cards() {
+        return this._discarded_cards;
+    }
+
+    // Append-only log of the round actions (deal, play, draw, zapzap)
+    get actions() {
+        return this._actions;
+    }
+
+    log_action(action) {
+        action.time = Date.now();
+        this._actions.push(action);
+    }
+
+    // Actions without the hidden cards (hands, draws from deck) until the round
+    // is over and hands are revealed
+    get public_actions() {
+        if (this._player_action==Round.ACTION_ZAPZAP)
+            return this._actions;
+
+        return this._actions.map(action => {
+            var public_action = Object.assign({}, action);
+            if (action.type=="deal")
+                public_action.hands = action.hands.map(hand => hand.length);
+            else if (action.type=="draw" && action.from=="deck")
+                public_action.card = -1;
+            return public_action;
+        });
+    }
+
     select_card(card = undefined) {
         this._selected_card = card;
     }
@@ -64,6 +101,7 @@ class Round {
         
         this._last_cards_played.forEach(discarded_card => {
             this._deck.discard(discarded_card);
+            this._discarded_cards.push(discarded_card);
         });
         this._last_cards_played = this._cards_played;
         this._cards_played = [];
@@ -54,6 +54,39 @@ get_card_id = function(card, deck=null) {
 }
 exports.get_card_id = get_card_id;
 
+const CARD_SUITS = ['♠', '♥', '♣', '♦'];
+const CARD_SUIT_LETTERS = ['s', 'h', 'c', 'd'];
+const CARD_RANKS = ['A', '2', '3', '4', '5', '6', '7', '8', '9', '10', 'J', 'Q', 'K'];
+
+// "K♥" for a card id, "Joker" for both jokers
+card_to_string = function(card_id) {
+    if (card_id>=52)
+        return "Joker";
+    if (card_id<0 || card_id==undefined)
+        return "?";
+    return CARD_RANKS[card_id%13] + CARD_SUITS[Math.trunc(card_id/13)];
+}
+exports.card_to_string = card_to_string;
+
+// card id from "10♦", "Kh" or "Joker" (first joker id), -1 if not a card
+exports.parse_card = function(str) {
+    str = str.trim();
+    if (str.toLowerCase()=="joker")
+        return 52;
+    var rank = CARD_RANKS.indexOf(str.slice(0, -1).toUpperCase());
+    var suit_str = str.slice(-1);
+    var suit = CARD_SUITS.indexOf(suit_str);
+    if (suit==-1)
+        suit = CARD_SUIT_LETTERS.indexOf(suit_str.toLowerCase());
+    if (rank==-1 || suit==-1)
+        return -1;
+    return suit*13 + rank;
+}
+
+exports.str_card_ids = function(card_ids) {
+    return card_ids.map(card_to_string).join(" ");
+}
+
 json_hand = function(hand, deck) {
     var json_ret = [];
     hand.forEach(card => {
@@ -64,8 +97,11 @@ json_hand = function(hand, deck) {
 }
 exports.json_hand = json_hand;
 
+// card id is a number or a string of digits, undefined unless between 0 and 53
 get_card_from_id = function(card_id, deck) {
-    if (card_id==-1) {
+    if (typeof card_id=="string" && /^\d+$/.test(card_id))
+        card_id = parseInt(card_id, 10);
+    if (!Number.isInteger(card_id) || card_id<0 || card_id>53) {
         return undefined;
     }
     if (card_id>=52) {
@@ -109,24 +145,46 @@ exports.get_card_from_id = get_card_from_id;
 
 
 
+// a single id (?cards=12) is read as a list of one card
 get_cards_from_ids = function(ids, deck) {
     var cards = [];
+    if (!Array.isArray(ids))
+        ids = [ids];
     ids.forEach(id => {
-        cards.push(get_card_from_id(parseInt(id,10), deck));
+        cards.push(get_card_from_id(id, deck));
     });
     return cards;
 }
 exports.get_cards_from_ids = get_cards_from_ids;
 
-exports.check_play = function(cards, player) {
+// Reasons returned by check_play_reason for an incorrect play
+const PLAY_ERRORS = {
+    no_cards: "No card selected",
+    unknown_card: "Unknown card",
+    duplicate_cards: "The same card is selected twice",
+    too_many_cards: "More cards than in hand",
+    not_in_hand: "Card is not in your hand",
+    mixed_ranks_and_suits: "Cards must share the same rank or the same suit"
+};
+exports.PLAY_ERRORS = PLAY_ERRORS;
+
+check_play_reason = function(cards, player) {
     // check size
-    if (cards.length<1 || cards.length>player.hand.length) {
-        return false;
-    } 
-    // TODO check in cards belong to the player hand
-    
     if (cards.length<1) {
-        return true;
+        return "no_cards";
+    }
+    if (cards.length>player.hand.length) {
+        return "too_many_cards";
+    }
+
+    // check cards belong to the player hand
+    for (var i=0; i<cards.length; i++) {
+        if (cards[i] == undefined)
+            return "unknown_card";
+        if (cards.indexOf(cards[i]) != i)
+            return "duplicate_cards";
+        if (!player.hand.includes(cards[i]))
+            return "not_in_hand";
     }
 
     // check suit and rank
@@ -141,9 +199,14 @@ exports.check_play = function(cards, player) {
             suit_check = false;
     });
 
-    // TODO check suit
+    if (!suit_check && !rank_check)
+        return "mixed_ranks_and_suits";
+    return undefined;
+}
+exports.check_play_reason = check_play_reason;
 
-    return suit_check || rank_check;
+exports.check_play = function(cards, player) {
+    return check_play_reason(cards, player) == undefined;
 }
 
 exports.get_card_points = function(card, withJoker) {
@@ -160,4 +223,224 @@ exports.get_card_points = function(card, withJoker) {
         case 'K': return 13;
         default : return parseInt(card.rank.shortName,10); 
     }
-}
\ No newline at end of file
+}
+
+// Cards of the same rank and of the same suit, jokers apart
+get_hand_groups = function(hand) {
+    var by_rank = {};
+    var by_suit = {};
+    var jokers = [];
+    hand.forEach(card => {
+        if (card.rank.shortName=='Joker') {
+            jokers.push(card);
+            return;
+        }
+        (by_rank[card.rank.shortName] = by_rank[card.rank.shortName] || []).push(card);
+        (by_suit[card.suit.unicode] = by_suit[card.suit.unicode] || []).push(card);
+    });
+    return {by_rank: Object.values(by_rank), by_suit: Object.values(by_suit), jokers: jokers};
+}
+
+sum_card_points = function(cards, withJoker=false) {
+    var points = 0;
+    cards.forEach(card => {
+        points += exports.get_card_points(card, withJoker);
+    });
+    return points;
+}
+
+// Key of a set of cards, the two jokers being interchangeable
+get_cards_key = function(cards) {
+    return cards.map(card => card.rank.shortName + (card.suit.unicode!=null?card.suit.unicode:"")).sort().join(",");
+}
+
+// Every subset of the hand which is a correct play, jokers last
+get_valid_plays = function(hand) {
+    var plays = [];
+    var keys = [];
+    for (var mask=1; mask < (1<<hand.length); mask++) {
+        var cards = hand.filter((card, i) => mask & (1<<i));
+        cards.sort((a, b) => (a.rank.shortName=='Joker') - (b.rank.shortName=='Joker'));
+        var key = get_cards_key(cards);
+        if (!keys.includes(key) && check_play_reason(cards, {hand: hand})==undefined) {
+            keys.push(key);
+            plays.push(cards);
+        }
+    }
+    return plays;
+}
+
+// Valid plays ranked by the points they remove from the hand, jokers counted
+// at the 25 points they cost when another player zapzap
+exports.suggest_plays = function(hand, deck, top_k=3) {
+    var suggestions = get_valid_plays(hand).map(cards => {
+        return {"cards": json_hand(cards, deck), "score": sum_card_points(cards, true)};
+    });
+    suggestions.sort((a, b) => b.score - a.score);
+    return suggestions.slice(0, top_k);
+}
+
+exports.analyze_hand = function(hand, deck, zapzap_max_points=5) {
+    var groups = get_hand_groups(hand);
+    var same_rank = groups.by_rank.filter(cards => cards.length>1);
+    var same_suit = groups.by_suit.filter(cards => cards.length>1);
+
+    // cards in no group can only be played with the jokers, or are deadwood
+    var lone_cards = hand.filter(card => card.rank.shortName!='Joker' &&
+        !same_rank.some(cards => cards.includes(card)) &&
+        !same_suit.some(cards => cards.includes(card)));
+    var with_jokers = groups.jokers.length>0 ? lone_cards.map(card => [card].concat(groups.jokers)) : [];
+    var deadwood = groups.jokers.length>0 ? [] : lone_cards;
+
+    // best play is the group removing the most points from the hand
+    var best_play = [];
+    get_valid_plays(hand).forEach(cards => {
+        if (sum_card_points(cards, true) > sum_card_points(best_play, true))
+            best_play = cards;
+    });
+
+    var value = sum_card_points(hand);
+    return {
+        "same_rank": same_rank.map(cards => json_hand(cards, deck)),
+        "same_suit": same_suit.map(cards => json_hand(cards, deck)),
+        "with_jokers": with_jokers.map(cards => json_hand(cards, deck)),
+        "jokers": json_hand(groups.jokers, deck),
+        "deadwood": json_hand(deadwood, deck),
+        "best_play": json_hand(best_play, deck),
+        "value": value,
+        "value_after_best_play": value - sum_card_points(best_play),
+        "zapzap": value<=zapzap_max_points
+    };
+}
+
+// Cards of the deck the player has not seen (in the draw pile or in opponents hands)
+get_unseen_cards = function(seen_cards, deck) {
+    return deck.findCards(card => !seen_cards.includes(card));
+}
+
+// Probability the next card drawn from the deck has the given rank short name
+exports.probability_of_drawing_rank = function(rank, seen_cards, deck) {
+    var unseen = get_unseen_cards(seen_cards, deck);
+    if (unseen.length==0)
+        return 0;
+    return unseen.filter(card => card.rank.shortName==rank).length / unseen.length;
+}
+
+// Expected points of the next card drawn from the deck
+exports.expected_draw_value = function(seen_cards, deck, withJoker=false) {
+    var unseen = get_unseen_cards(seen_cards, deck);
+    if (unseen.length==0)
+        return 0;
+    var points = 0;
+    unseen.forEach(card => {
+        points += exports.get_card_points(card, withJoker);
+    });
+    return points / unseen.length;
+}
+
+// Probability an opponent holding nb_cards unseen cards has at least one worth max_points or less
+exports.probability_opponent_holds_at_most = function(max_points, nb_cards, seen_cards, deck) {
+    var unseen = get_unseen_cards(seen_cards, deck);
+    var high = unseen.filter(card => exports.get_card_points(card, false) > max_points).length;
+    // probability all nb_cards are drawn from the high cards
+    var p_none = 1;
+    for (var i=0; i<nb_cards; i++) {
+        if (unseen.length-i <= 0)
+            break;
+        p_none *= Math.max(high-i, 0) / (unseen.length-i);
+    }
+    return 1 - p_none;
+}
+
+// Monte Carlo estimation of the probability the hand is the lowest of the table,
+// i.e. a zapzap now would not be counteracted. Opponent hands are sampled from
+// the cards neither in the hand nor in known_cards.
+exports.estimate_win_probability = function(hand, opponents_hand_sizes, known_cards, deck, samples=1000) {
+    var unseen = get_unseen_cards(hand.concat(known_cards), deck);
+    var nb_cards = opponents_hand_sizes.reduce((total, size) => total + size, 0);
+    if (nb_cards > unseen.length || samples<1)
+        return 0;
+
+    var hand_points = sum_card_points(hand);
+    var wins = 0;
+    for (var sample=0; sample<samples; sample++) {
+        // partial shuffle of the first nb_cards unseen cards
+        for (var i=0; i<nb_cards; i++) {
+            var j = i + Math.floor(Math.random() * (unseen.length - i));
+            [unseen[i], unseen[j]] = [unseen[j], unseen[i]];
+        }
+        // an opponent with as few points counteracts
+        var lowest = true;
+        var start = 0;
+        opponents_hand_sizes.forEach(size => {
+            if (sum_card_points(unseen.slice(start, start + size)) <= hand_points)
+                lowest = false;
+            start += size;
+        });
+        if (lowest)
+            wins++;
+    }
+    return wins / samples;
+}
+
+// Largest endgame solve_endgame accepts, the states grow with the deck at each turn
+const ENDGAME_MAX_CARDS = 6;
+const ENDGAME_MAX_HORIZON = 2;
+
+// Exact play and draw policy minimizing the expected hand points after horizon
+// turns. Each turn a valid play is made then a card is drawn, either one of the
+// visible cards (only known for the first turn) or a card of deck_composition at
+// random. Returns {play, draw, value} where draw is undefined for the deck, or
+// {value} when no turn can be played.
+exports.solve_endgame = function(hand, visible, deck_composition, horizon) {
+    if (hand.length>ENDGAME_MAX_CARDS || !Number.isInteger(horizon) || horizon<0 || horizon>ENDGAME_MAX_HORIZON)
+        throw new Error("Endgame too large : " + ENDGAME_MAX_CARDS + " cards and " + ENDGAME_MAX_HORIZON + " turns at most");
+    var memo = new Map();
+
+    var solve = function(hand, visible, deck, turns) {
+        if (turns<=0)
+            return {value: sum_card_points(hand)};
+        var key = [get_cards_key(hand), get_cards_key(visible), get_cards_key(deck), turns].join("|");
+        if (memo.has(key))
+            return memo.get(key);
+
+        // distinct cards of the deck with their number of copies
+        var deck_cards = new Map();
+        deck.forEach(card => {
+            var card_key = get_cards_key([card]);
+            if (!deck_cards.has(card_key))
+                deck_cards.set(card_key, {card: card, count: 0});
+            deck_cards.get(card_key).count++;
+        });
+
+        var best = undefined;
+        get_valid_plays(hand).forEach(play => {
+            var rest = hand.filter(card => !play.includes(card));
+
+            visible.forEach(card => {
+                var value = solve(rest.concat([card]), [], deck, turns-1).value;
+                if (best==undefined || value<best.value)
+                    best = {play: play, draw: card, value: value};
+            });
+
+            if (deck.length>0) {
+                var value = 0;
+                deck_cards.forEach(({card, count}) => {
+                    var next_deck = deck.slice();
+                    next_deck.splice(next_deck.indexOf(card), 1);
+                    value += count / deck.length * solve(rest.concat([card]), [], next_deck, turns-1).value;
+                });
+                if (best==undefined || value<best.value)
+                    best = {play: play, draw: undefined, value: value};
+            }
+        });
+
+        // nothing to draw : the round can't go on
+        if (best==undefined)
+            best = {value: sum_card_points(hand)};
+        memo.set(key, best);
+        return best;
+    };
+
+    return solve(hand, visible, deck_composition, horizon);
+}